@@ -0,0 +1,210 @@
+use equation::Equation;
+use model::Molecule;
+
+/// An exact rational number stored as a reduced numerator/denominator
+/// pair, so that balancing an equation via Gaussian elimination never
+/// accumulates floating point error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Fraction {
+    num: i64,
+    den: i64,
+}
+
+impl Fraction {
+    fn new(num: i64, den: i64) -> Fraction {
+        assert!(den != 0, "Fraction denominator cannot be zero");
+        let g = gcd(num.abs(), den.abs()).max(1);
+        let sign = if den < 0 { -1 } else { 1 };
+        Fraction { num: sign * num / g, den: sign * den / g }
+    }
+
+    fn from_int(n: i64) -> Fraction {
+        Fraction::new(n, 1)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    fn sub(&self, other: &Fraction) -> Fraction {
+        Fraction::new(self.num * other.den - other.num * self.den, self.den * other.den)
+    }
+
+    fn mul(&self, other: &Fraction) -> Fraction {
+        Fraction::new(self.num * other.num, self.den * other.den)
+    }
+
+    fn div(&self, other: &Fraction) -> Fraction {
+        Fraction::new(self.num * other.den, self.den * other.num)
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn lcm(a: i64, b: i64) -> i64 {
+    a / gcd(a, b) * b
+}
+
+/// Balance a chemical equation, returning one positive integer
+/// coefficient per compound, reactants first then products, in the same
+/// order as `equation.reactants` followed by `equation.products`.
+///
+/// ```text
+/// Example H2 + O2 -> H2O
+///         balance(..) -> [2, 1, 2]
+/// ```
+///
+/// Builds a matrix with one row per distinct element and one column per
+/// compound, where entry (element, compound) is the atom count of that
+/// element in that compound (negated for products), then finds a
+/// nonzero integer vector in its null space.
+pub fn balance(equation: &Equation) -> Result<Vec<i64>, String> {
+    let compounds: Vec<&Molecule> = equation.reactants.iter()
+        .chain(equation.products.iter())
+        .collect();
+    let reactant_count = equation.reactants.len();
+
+    let mut elements: Vec<&str> = Vec::new();
+    for molecule in &compounds {
+        for atom in molecule.iter() {
+            if !elements.contains(&atom.0.as_str()) {
+                elements.push(&atom.0);
+            }
+        }
+    }
+
+    let mut matrix: Vec<Vec<Fraction>> = elements.iter()
+        .map(|element| {
+            compounds.iter().enumerate()
+                .map(|(col, molecule)| {
+                    let count = molecule.iter()
+                        .find(|atom| atom.0 == *element)
+                        .map_or(0, |atom| atom.1 as i64);
+                    let signed = if col < reactant_count { count } else { -count };
+                    Fraction::from_int(signed)
+                })
+                .collect()
+        })
+        .collect();
+
+    let free_column = reduce_to_rref(&mut matrix, compounds.len())?;
+
+    let mut solution = vec![Fraction::from_int(0); compounds.len()];
+    solution[free_column] = Fraction::from_int(1);
+
+    // Each pivot row has the form x_pivot + sum(coeff * x_other) = 0, so
+    // the pivot's value falls out once every other column is known.
+    for row in matrix.iter().rev() {
+        if let Some(pivot) = row.iter().position(|f| !f.is_zero()) {
+            if pivot == free_column {
+                continue;
+            }
+            let mut value = Fraction::from_int(0);
+            for (col, coeff) in row.iter().enumerate() {
+                if col != pivot && !coeff.is_zero() {
+                    value = value.sub(&coeff.mul(&solution[col]));
+                }
+            }
+            solution[pivot] = value.div(&row[pivot]);
+        }
+    }
+
+    let coefficients = to_smallest_integers(&solution);
+    if coefficients.iter().any(|&n| n <= 0) {
+        return Err("Equation cannot be balanced with positive coefficients".to_string());
+    }
+    Ok(coefficients)
+}
+
+/// Reduce `matrix` in place to reduced row-echelon form and return the
+/// index of the single free (non-pivot) column. Exactly one free column
+/// means the null space has dimension 1, as a balanced equation needs;
+/// zero or several free columns are returned as an error.
+fn reduce_to_rref(matrix: &mut Vec<Vec<Fraction>>, num_cols: usize) -> Result<usize, String> {
+    let mut pivot_cols: Vec<usize> = Vec::new();
+    let mut row = 0;
+    for col in 0..num_cols {
+        if row >= matrix.len() {
+            break;
+        }
+        let pivot_row = (row..matrix.len()).find(|&r| !matrix[r][col].is_zero());
+        let pivot_row = match pivot_row {
+            Some(r) => r,
+            None => continue,
+        };
+        matrix.swap(row, pivot_row);
+
+        let pivot_value = matrix[row][col];
+        for c in col..num_cols {
+            matrix[row][c] = matrix[row][c].div(&pivot_value);
+        }
+
+        for r in 0..matrix.len() {
+            if r != row && !matrix[r][col].is_zero() {
+                let factor = matrix[r][col];
+                for c in col..num_cols {
+                    let subtrahend = matrix[row][c].mul(&factor);
+                    matrix[r][c] = matrix[r][c].sub(&subtrahend);
+                }
+            }
+        }
+
+        pivot_cols.push(col);
+        row += 1;
+    }
+
+    let free_cols: Vec<usize> = (0..num_cols).filter(|c| !pivot_cols.contains(c)).collect();
+    match free_cols.as_slice() {
+        &[] => Err("Equation cannot be balanced: no solution exists".to_string()),
+        &[free] => Ok(free),
+        _ => Err("Equation is ambiguous: more than one independent balancing exists".to_string()),
+    }
+}
+
+/// Scale a rational solution vector up to the smallest positive
+/// integers: multiply through by the LCM of all denominators, then
+/// divide by the GCD of the resulting integers.
+fn to_smallest_integers(solution: &[Fraction]) -> Vec<i64> {
+    let denom_lcm = solution.iter().fold(1i64, |acc, f| lcm(acc, f.den));
+    let mut scaled: Vec<i64> = solution.iter()
+        .map(|f| f.num * (denom_lcm / f.den))
+        .collect();
+
+    let overall_gcd = scaled.iter().fold(0i64, |acc, &n| gcd(acc, n.abs()));
+    if overall_gcd > 1 {
+        scaled = scaled.iter().map(|n| n / overall_gcd).collect();
+    }
+
+    if scaled.iter().any(|&n| n < 0) {
+        scaled = scaled.iter().map(|n| -n).collect();
+    }
+
+    scaled
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use equation::parse_equation;
+
+    #[test]
+    fn balances_hydrogen_combustion() {
+        let equation = parse_equation("H2 + O2 -> H2O").unwrap();
+        assert_eq!(balance(&equation).unwrap(), vec![2, 1, 2]);
+    }
+
+    #[test]
+    fn balances_permanganate_reaction() {
+        let equation = parse_equation("KMnO4 + HCl -> KCl + MnCl2 + H2O + Cl2").unwrap();
+        let coefficients = balance(&equation).unwrap();
+        assert_eq!(coefficients, vec![2, 16, 2, 2, 8, 5]);
+    }
+
+    #[test]
+    fn rejects_unbalanceable_equation() {
+        let equation = parse_equation("H2 -> O2").unwrap();
+        assert!(balance(&equation).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use lexer::{self, Token, TokenKind};
+use model::Molecule;
+use parser;
+
+/// A chemical equation split into its reactant and product molecules,
+/// e.g. `H2 + O2 -> H2O` becomes reactants `[H2, O2]` and products `[H2O]`.
+#[derive(Debug, Clone)]
+pub struct Equation {
+    pub reactants: Vec<Molecule>,
+    pub products: Vec<Molecule>,
+}
+
+/// Parse a full chemical equation such as `H2 + O2 -> H2O` or
+/// `KMnO4 + HCl = KCl + MnCl2 + H2O + Cl2` into its reactant and product
+/// molecules. The coefficients are not filled in here; see
+/// `balance::balance` for that.
+pub fn parse_equation(s: &str) -> Result<Equation, String> {
+    let tokens = lexer::lex(s).map_err(|e| e.render(s))?;
+    let (reactant_tokens, product_tokens) = split_on_arrow(&tokens)?;
+
+    let reactants = split_on_plus(reactant_tokens).iter()
+        .map(|side| parser::parse_tokens(s, side))
+        .collect::<Result<Vec<Molecule>, String>>()?;
+    let products = split_on_plus(product_tokens).iter()
+        .map(|side| parser::parse_tokens(s, side))
+        .collect::<Result<Vec<Molecule>, String>>()?;
+
+    Ok(Equation { reactants, products })
+}
+
+/// Split a token stream into the tokens before and after the single
+/// reaction arrow (`->` or `=`).
+fn split_on_arrow(tokens: &[Token]) -> Result<(&[Token], &[Token]), String> {
+    let arrow_positions: Vec<usize> = tokens.iter()
+        .enumerate()
+        .filter(|&(_, token)| match token.kind {
+            TokenKind::Arrow => true,
+            _ => false,
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    match arrow_positions.as_slice() {
+        &[pos] => Ok((&tokens[..pos], &tokens[pos + 1..])),
+        &[] => Err("Expected a reaction arrow ('->' or '=') but found none".to_string()),
+        _ => Err("Expected a single reaction arrow but found more than one".to_string()),
+    }
+}
+
+/// Split a token stream on `+` tokens into the token slices for each
+/// compound on that side of the equation.
+fn split_on_plus(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut sides = Vec::new();
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        if let TokenKind::Plus = token.kind {
+            sides.push(&tokens[start..i]);
+            start = i + 1;
+        }
+    }
+    sides.push(&tokens[start..]);
+    sides
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_equations_regardless_of_spacing_around_plus() {
+        for formula in &["H2 + O2 -> H2O", "H2+O2->H2O", "H2+ O2 -> H2O", "H2 +O2 -> H2O"] {
+            let equation = parse_equation(formula).unwrap_or_else(|e| panic!("{} failed to parse: {}", formula, e));
+            assert_eq!(equation.reactants.len(), 2);
+            assert_eq!(equation.products.len(), 1);
+        }
+    }
+}
@@ -1,65 +1,297 @@
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
+
+/// A byte offset and length into the original input string, attached to
+/// every `Token` so that parse errors can point back at the exact
+/// characters that caused them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, len: usize) -> Span {
+        Span { start, len }
+    }
+}
+
+/// An error raised while lexing, still tagged with the span it applies
+/// to so a caller with the original input can render a caret diagnostic
+/// via `render_error`.
+#[derive(Debug, Clone)]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl LexError {
+    fn new(span: Span, message: String) -> LexError {
+        LexError { span, message }
+    }
+
+    /// Render this error as a caret diagnostic against the original input.
+    pub fn render(&self, input: &str) -> String {
+        render_error(input, self.span, &self.message)
+    }
+}
 
 /// Molecule syntax tokens recognizable by our Lexer
 #[derive(Debug, Clone)]
-pub enum Token {
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// The kind of a lexed token, without its source position
+#[derive(Debug, Clone)]
+pub enum TokenKind {
     Bracket(char),
     Atom(String),
     Index(usize),
+    Plus,
+    Arrow,
+    /// A net ion charge, e.g. `^2-` or a bare `+`/`-`, already folded
+    /// into a single signed value (`SO4^2-` -> `-2`, `NH4+` -> `1`).
+    Charge(i32),
+    /// A hydrate separator: `.` or the middle dot `·`, e.g. the `·` in
+    /// `CuSO4·5H2O`.
+    DotSeparator,
 }
 
 /// Lex a string slice into a Vector of Tokens
-pub fn lex(input: &str) -> Result<Vec<Token>, String> {
+pub fn lex(input: &str) -> Result<Vec<Token>, LexError> {
     let mut result = Vec::new();
 
-    let mut it = input.chars().peekable();
-    while let Some(&c) = it.peek() {
+    let mut it = input.char_indices().peekable();
+    while let Some(&(start, c)) = it.peek() {
         match c {
             'A'...'Z' => {
                 it.next();
-                let n = lex_atom(c, &mut it);
-                result.push(Token::Atom(n));
+                let (n, end) = lex_atom(start, c, &mut it);
+                result.push(Token { kind: TokenKind::Atom(n), span: Span::new(start, end - start) });
             }
             '0'...'9' => {
                 it.next();
-                let n = lex_index(c, &mut it);
-                result.push(Token::Index(n));
+                let (n, end) = lex_index(start, c, &mut it);
+                result.push(Token { kind: TokenKind::Index(n), span: Span::new(start, end - start) });
             }
             '(' | ')' | '[' | ']' | '{' | '}' => {
-                result.push(Token::Bracket(c));
+                result.push(Token { kind: TokenKind::Bracket(c), span: Span::new(start, 1) });
+                it.next();
+            }
+            '.' | '\u{b7}' => {
+                result.push(Token { kind: TokenKind::DotSeparator, span: Span::new(start, c.len_utf8()) });
+                it.next();
+            }
+            '^' => {
+                it.next();
+                result.push(lex_charge(start, &mut it)?);
+            }
+            '+' => {
+                it.next();
+                // A `+` immediately followed by the start of another
+                // compound, an atom or an opening bracket (ignoring
+                // whitespace), is a reaction separator like `H2 + O2`,
+                // `H2+ O2` or the unspaced `H2+O2`; any other `+` is a
+                // trailing ion charge like `NH4+`.
+                if is_separator_position(input, start) {
+                    result.push(Token { kind: TokenKind::Plus, span: Span::new(start, 1) });
+                } else {
+                    result.push(Token { kind: TokenKind::Charge(1), span: Span::new(start, 1) });
+                }
+            }
+            '=' => {
+                result.push(Token { kind: TokenKind::Arrow, span: Span::new(start, 1) });
+                it.next();
+            }
+            '-' => {
+                it.next();
+                match it.peek().cloned() {
+                    Some((_, '>')) => {
+                        it.next();
+                        result.push(Token { kind: TokenKind::Arrow, span: Span::new(start, 2) });
+                    }
+                    _ => result.push(Token { kind: TokenKind::Charge(-1), span: Span::new(start, 1) }),
+                }
+            }
+            ' ' => {
                 it.next();
             }
             _ => {
-                return Err(format!("Unexpected character {}", c));
+                let message = format!("unexpected character '{}'", c);
+                return Err(LexError::new(Span::new(start, c.len_utf8()), message));
             }
         }
     }
     Ok(result)
 }
 
-/// Lex an atom
-fn lex_atom(c: char, iter: &mut Peekable<Chars>) -> String {
+/// Whether the `+` at byte offset `start` reads as a standalone reaction
+/// separator rather than a charge sign attached to a formula. This looks
+/// only at what follows the `+` (skipping any whitespace): another atom
+/// or a bracketed group starting right after it means a new
+/// reactant/product is beginning, regardless of whether there is
+/// whitespace around the `+` itself.
+fn is_separator_position(input: &str, start: usize) -> bool {
+    let after = start + '+'.len_utf8();
+    input[after..].chars().find(|c| !c.is_whitespace())
+        .map_or(false, |c| c.is_uppercase() || matches!(c, '(' | '[' | '{'))
+}
+
+/// Lex a charge that starts with `^`, e.g. `^2-` or `^+`: an optional
+/// magnitude (defaulting to 1) followed by a mandatory sign.
+fn lex_charge(start: usize, iter: &mut Peekable<CharIndices>) -> Result<Token, LexError> {
+    let mut magnitude: i32 = 1;
+    let mut has_digit = false;
+    while let Some(&(_, d)) = iter.peek() {
+        match d.to_digit(10) {
+            Some(digit) => {
+                if !has_digit {
+                    magnitude = 0;
+                    has_digit = true;
+                }
+                magnitude = magnitude * 10 + digit as i32;
+                iter.next();
+            }
+            None => break,
+        }
+    }
+
+    match iter.next() {
+        Some((pos, '+')) => Ok(Token { kind: TokenKind::Charge(magnitude), span: Span::new(start, pos + 1 - start) }),
+        Some((pos, '-')) => Ok(Token { kind: TokenKind::Charge(-magnitude), span: Span::new(start, pos + 1 - start) }),
+        _ => Err(LexError::new(Span::new(start, 1), "expected '+' or '-' after a charge magnitude".to_string())),
+    }
+}
+
+/// Lex an atom, returning its symbol and the byte offset just past it
+fn lex_atom(start: usize, c: char, iter: &mut Peekable<CharIndices>) -> (String, usize) {
     let mut atom = c.to_string();
-    while let Some(Ok(character)) = iter.peek()
-        .map(|c| {
-            match *c {
-                'a'...'z' => Ok(c.to_string()),
-                _ => Err(())
+    let mut end = start + c.len_utf8();
+    while let Some(&(pos, character)) = iter.peek() {
+        match character {
+            'a'...'z' => {
+                atom.push(character);
+                end = pos + character.len_utf8();
+                iter.next();
+            }
+            _ => break,
+        }
+    }
+    (atom, end)
+}
+
+/// Lex an atom index, returning its value and the byte offset just past it
+fn lex_index(start: usize, c: char, iter: &mut Peekable<CharIndices>) -> (usize, usize) {
+    let mut number: usize = c.to_string().parse().expect("Expected digit");
+    let mut end = start + c.len_utf8();
+    while let Some(&(pos, digit)) = iter.peek() {
+        match digit.to_digit(10) {
+            Some(d) => {
+                number = number * 10 + d as usize;
+                end = pos + digit.len_utf8();
+                iter.next();
             }
-        }) {
-        atom = format!("{}{}", atom, character);
-        iter.next();
+            None => break,
+        }
     }
-    atom
+    (number, end)
+}
+
+/// Render `(input, span, message)` into a multi-line diagnostic that
+/// reprints the input with a caret underline beneath the offending span.
+///
+/// ```text
+/// Mg(OH}2
+///      ^
+/// mismatched bracket: expected ')'
+/// ```
+pub fn render_error(input: &str, span: Span, message: &str) -> String {
+    // `span` is a byte offset/length, but the underline is built out of
+    // display characters, so a multi-byte character before the span
+    // (e.g. the hydrate `·`) would otherwise shift the caret off by one.
+    let span_end = (span.start + span.len).min(input.len());
+    let leading_chars = input[..span.start.min(input.len())].chars().count();
+    let span_chars = input[span.start.min(span_end)..span_end].chars().count();
+    let underline: String = (0..leading_chars).map(|_| ' ')
+        .chain((0..span_chars.max(1)).map(|_| '^'))
+        .collect();
+    format!("{}\n{}\n{}", input, underline, message)
 }
 
-/// Lex atom index
-fn lex_index(c: char, iter: &mut Peekable<Chars>) -> usize {
-    let mut number = c.to_string().parse().expect("Expected digit");
-    while let Some(Ok(digit)) = iter.peek().map(|c| c.to_string().parse::<usize>()) {
-        number = number * 10 + digit;
-        iter.next();
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_error_points_at_the_span() {
+        let rendered = render_error("Mg(OH}2", Span::new(5, 1), "mismatched bracket: expected ')'");
+        assert_eq!(rendered, "Mg(OH}2\n     ^\nmismatched bracket: expected ')'");
+    }
+
+    #[test]
+    fn lexes_caret_and_bare_charges() {
+        let tokens = lex("SO4^2-").unwrap();
+        match tokens.last().unwrap().kind {
+            TokenKind::Charge(-2) => {},
+            ref other => panic!("expected Charge(-2), got {:?}", other),
+        }
+
+        let tokens = lex("NH4+").unwrap();
+        match tokens.last().unwrap().kind {
+            TokenKind::Charge(1) => {},
+            ref other => panic!("expected Charge(1), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn still_splits_equations_on_a_spaced_plus() {
+        let tokens = lex("H2 + O2").unwrap();
+        assert!(tokens.iter().any(|t| match t.kind {
+            TokenKind::Plus => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn splits_equations_on_an_unspaced_plus() {
+        for formula in &["H2+O2", "H2+ O2", "H2 +O2"] {
+            let tokens = lex(formula).unwrap();
+            assert!(tokens.iter().any(|t| match t.kind {
+                TokenKind::Plus => true,
+                _ => false,
+            }), "expected a Plus token for {:?}, got {:?}", formula, tokens);
+        }
+    }
+
+    #[test]
+    fn splits_equations_on_a_plus_before_a_bracketed_compound() {
+        for formula in &["NaOH+(NH4)2SO4", "NaOH + (NH4)2SO4", "NaOH+ [NH4]2SO4"] {
+            let tokens = lex(formula).unwrap();
+            assert!(tokens.iter().any(|t| match t.kind {
+                TokenKind::Plus => true,
+                _ => false,
+            }), "expected a Plus token for {:?}, got {:?}", formula, tokens);
+        }
+    }
+
+    #[test]
+    fn render_error_counts_chars_not_bytes_across_multibyte_input() {
+        // `\u{b7}` (the hydrate middle dot) is 2 bytes but 1 display
+        // character; the caret must still land under the 8th character.
+        let rendered = render_error("CuSO4\u{b7}5Xy", Span::new(8, 2), "unknown element 'Xy'");
+        assert_eq!(rendered, "CuSO4\u{b7}5Xy\n       ^^\nunknown element 'Xy'");
     }
-    number
-}
\ No newline at end of file
+
+    #[test]
+    fn lexes_hydrate_dot_separators() {
+        for formula in &["CuSO4.5H2O", "CuSO4\u{b7}5H2O"] {
+            let tokens = lex(formula).unwrap();
+            assert!(tokens.iter().any(|t| match t.kind {
+                TokenKind::DotSeparator => true,
+                _ => false,
+            }));
+        }
+    }
+}
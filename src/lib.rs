@@ -0,0 +1,10 @@
+//! A parser for chemical formulas and equations: molar mass, hydrates,
+//! ion charges, and equation balancing, plus a configurable
+//! `parse_molecule_with` for consumers that want the syntax tree or
+//! finer control over validation than the convenience functions give.
+
+pub mod balance;
+pub mod equation;
+pub mod lexer;
+pub mod model;
+pub mod parser;
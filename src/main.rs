@@ -1,14 +1,60 @@
+extern crate molecule_parser;
+
 use std::env;
 
-mod lexer;
-mod model;
-mod parser;
+use molecule_parser::{balance, equation, model, parser};
 
 fn main() {
-    let molecule = env::args().nth(1).unwrap_or_else(|| {
-        eprintln!("Please provide molecule as argument");
+    let mut args = env::args().skip(1);
+    let first = args.next().unwrap_or_else(|| {
+        eprintln!("Please provide a molecule or equation as argument");
         ::std::process::exit(1);
     });
 
-    println!("Atoms: {:?}", parser::parse_molecule(&molecule));
+    if first == "mass" {
+        let formula = args.next().unwrap_or_else(|| {
+            eprintln!("Please provide a formula after 'mass'");
+            ::std::process::exit(1);
+        });
+        run_mass(&formula);
+    } else if first.contains('+') || first.contains("->") || first.contains('=') {
+        run_equation(&first);
+    } else {
+        println!("Atoms: {:?}", parser::parse_molecule(&first));
+    }
+}
+
+fn run_mass(formula: &str) {
+    let molecule = match parser::parse_molecule(formula) {
+        Ok(molecule) => molecule,
+        Err(e) => {
+            eprintln!("Error parsing molecule: {}", e);
+            ::std::process::exit(1);
+        }
+    };
+    let mass = model::molar_mass(&molecule);
+
+    println!("Molar mass: {:.3} g/mol", mass);
+    for &(ref symbol, count) in &molecule {
+        let weight = model::atomic_weight(symbol).unwrap_or(0.0);
+        println!("  {} x{}: {:.3} x {} = {:.3}", symbol, count, weight, count, weight * (count as f64));
+    }
+}
+
+fn run_equation(input: &str) {
+    let equation = match equation::parse_equation(input) {
+        Ok(equation) => equation,
+        Err(e) => {
+            eprintln!("Error parsing equation: {}", e);
+            ::std::process::exit(1);
+        }
+    };
+
+    match balance::balance(&equation) {
+        Ok(coefficients) => println!("Coefficients: {:?}", coefficients),
+        Err(e) => {
+            eprintln!("Error balancing equation: {}", e);
+            ::std::process::exit(1);
+        }
+    }
 }
@@ -1,18 +1,18 @@
-use lexer::Token;
-use model::{Atom, Molecule, add_atoms, mul_molecule};
+use lexer::{self, render_error, Span, Token, TokenKind};
+use model::{self, Atom, Molecule, add_atoms, mul_molecule};
 
 /// Grammar nodes supported by our syntax tree
 #[derive(Debug,Clone)]
-enum Grammar {
+pub enum Grammar {
     Atom(Atom),
     Index(usize)
 }
 
 /// Tree structure that represents a molecule
 #[derive(Debug,Clone)]
-struct ParseNode {
-    children: Vec<ParseNode>,
-    entry: Grammar,
+pub struct ParseNode {
+    pub children: Vec<ParseNode>,
+    pub entry: Grammar,
 }
 
 /// Tree structure to represent a molecule
@@ -39,16 +39,115 @@ impl ParseNode {
             Grammar::Atom(ref atom) => vec![atom.clone()]
         }
     }
+
+    /// Fold the tree directly into a molar mass, mirroring `flatten` but
+    /// without building the intermediate `Molecule` vector first.
+    pub fn mass(&self) -> f64 {
+        match self.entry {
+            Grammar::Index(ref index) => {
+                let sum: f64 = self.children.iter().map(|child| child.mass()).sum();
+                sum * (*index as f64)
+            },
+            Grammar::Atom(ref atom) => {
+                model::atomic_weight(&atom.0).unwrap_or(0.0) * (atom.1 as f64)
+            }
+        }
+    }
+}
+
+/// What went wrong inside a `SyntaxError`, kept separate from its
+/// message so callers can distinguish an unknown element from any other
+/// syntax mistake without string-matching.
+enum SyntaxErrorKind {
+    Generic,
+    UnknownElement(String),
+}
+
+/// An internal parse error still tagged with the source span it applies
+/// to, so it can be rendered as a caret diagnostic, or converted into
+/// the public `ParseError` enum, once the caller has the original input.
+struct SyntaxError {
+    span: Span,
+    message: String,
+    kind: SyntaxErrorKind,
+}
+
+impl SyntaxError {
+    fn new(span: Span, message: String) -> SyntaxError {
+        SyntaxError { span, message, kind: SyntaxErrorKind::Generic }
+    }
+
+    fn unknown_element(span: Span, symbol: String) -> SyntaxError {
+        let message = format!("unknown element '{}'", symbol);
+        SyntaxError { span, message, kind: SyntaxErrorKind::UnknownElement(symbol) }
+    }
+}
+
+/// Build the `SyntaxError` for a token that was found where none of the
+/// expected kinds were, or for running off the end of the input.
+fn unexpected_token(tokens: &[Token], pos: usize) -> SyntaxError {
+    match tokens.get(pos) {
+        Some(token) => SyntaxError::new(token.span, format!("unexpected token {:?}", token.kind)),
+        None => SyntaxError::new(end_of_input(tokens), "unexpected end of input".to_string()),
+    }
+}
+
+/// A one-character span just past the last token, used to point errors
+/// at the end of the input when no token is left to blame.
+fn end_of_input(tokens: &[Token]) -> Span {
+    let end = tokens.last().map_or(0, |t| t.span.start + t.span.len);
+    Span::new(end, 1)
+}
+
+/// Errors a library consumer can match on, as an alternative to the
+/// caret-rendered `String` errors `parse_molecule` and friends return.
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    /// The input contained a character (or character sequence) the
+    /// lexer doesn't recognise.
+    Lex { span: Span, message: String },
+    /// The token stream didn't match the grammar.
+    Parse { span: Span, message: String },
+    /// An atom symbol was lexed but isn't in the periodic table.
+    UnknownElement { span: Span, symbol: String },
+}
+
+impl ParseError {
+    /// Render this error as a caret diagnostic against the original input.
+    pub fn render(&self, input: &str) -> String {
+        match *self {
+            ParseError::Lex { span, ref message } => render_error(input, span, message),
+            ParseError::Parse { span, ref message } => render_error(input, span, message),
+            ParseError::UnknownElement { span, ref symbol } => {
+                render_error(input, span, &format!("unknown element '{}'", symbol))
+            }
+        }
+    }
+}
+
+impl From<lexer::LexError> for ParseError {
+    fn from(e: lexer::LexError) -> ParseError {
+        ParseError::Lex { span: e.span, message: e.message }
+    }
+}
+
+impl From<SyntaxError> for ParseError {
+    fn from(e: SyntaxError) -> ParseError {
+        match e.kind {
+            SyntaxErrorKind::UnknownElement(symbol) => ParseError::UnknownElement { span: e.span, symbol },
+            SyntaxErrorKind::Generic => ParseError::Parse { span: e.span, message: e.message },
+        }
+    }
 }
 
 /// After flattening the tree we can end up with a result similar to this
-/// ```
+/// ```text
 /// [("K", 4), ("O", 2), ("N", 2), ("S", 4), ("O", 12)]
 /// ```
 /// where oxygen has two entries.
 ///
 /// After running this function we end up with:
-/// ```
+/// ```text
 /// [("K", 4), ("O", 14), ("N", 2), ("S", 4)]
 /// ```
 fn merge_atoms(molecule: &Molecule) -> Molecule {
@@ -72,18 +171,33 @@ fn merge_atoms(molecule: &Molecule) -> Molecule {
     deduplicated_atoms
 }
 
-/// Build a ParseNode tree from tokens
-fn parse_atoms(tokens: &[Token], pos: usize) -> Result<(ParseNode, usize), String> {
+/// Order atoms using the Hill system: carbon first, hydrogen second,
+/// then every other element alphabetically by symbol.
+fn hill_sort(molecule: &mut Molecule) {
+    molecule.sort_by(|a, b| hill_key(&a.0).cmp(&hill_key(&b.0)));
+}
+
+fn hill_key(symbol: &str) -> (u8, &str) {
+    match symbol {
+        "C" => (0, symbol),
+        "H" => (1, symbol),
+        _ => (2, symbol),
+    }
+}
+
+/// Build a ParseNode tree from tokens. `validate` controls whether atom
+/// symbols must exist in the periodic table (see `ParserConfig`).
+fn parse_atoms(tokens: &[Token], pos: usize, validate: bool) -> Result<(ParseNode, usize), SyntaxError> {
     let mut cur_pos = pos;
     let mut children = vec![];
 
     loop {
-        let (parse_node, new_pos) = match tokens.get(cur_pos) {
-            Some(&Token::Atom(_)) => {
-                parse_atom(&tokens, cur_pos)?
+        let (parse_node, new_pos) = match tokens.get(cur_pos).map(|t| &t.kind) {
+            Some(&TokenKind::Atom(_)) => {
+                parse_atom(&tokens, cur_pos, validate)?
             },
-            Some(&Token::Bracket('(')) | Some(&Token::Bracket('[')) | Some(&Token::Bracket('{')) => {
-                parse_group(&tokens, cur_pos)?
+            Some(&TokenKind::Bracket('(')) | Some(&TokenKind::Bracket('[')) | Some(&TokenKind::Bracket('{')) => {
+                parse_group(&tokens, cur_pos, validate)?
             }
             _ => break
         };
@@ -100,76 +214,80 @@ fn parse_atoms(tokens: &[Token], pos: usize) -> Result<(ParseNode, usize), Strin
 
 /// Parse exactly one atom and its index (if present)
 ///
-/// ```
+/// ```text
 /// Example K4[ON(SO3)2]2
 ///            ^^ ^ These are three atoms without indices
 ///         ^^     ^^ These atoms have indices
 /// ```
-fn parse_atom(tokens: &[Token], pos: usize) -> Result<(ParseNode, usize), String> {
-    if let Some(&Token::Atom(ref a)) = tokens.get(pos) {
-        parse_index(tokens, pos + 1).and_then(|(index, next_pos)| {
+fn parse_atom(tokens: &[Token], pos: usize, validate: bool) -> Result<(ParseNode, usize), SyntaxError> {
+    if let Some(token) = tokens.get(pos) {
+        if let TokenKind::Atom(ref a) = token.kind {
+            if validate && model::atomic_weight(a).is_none() {
+                return Err(SyntaxError::unknown_element(token.span, a.clone()));
+            }
+            let (index, next_pos) = parse_index(tokens, pos + 1)?;
             let atom = Grammar::Atom((a.clone(), index));
             let parse_node = ParseNode::new(vec![], atom);
-            Ok((parse_node, next_pos))
-        })
-    } else {
-        Err(format!("Unexpected token {:?}", tokens.get(pos)))
+            return Ok((parse_node, next_pos));
+        }
     }
+    Err(unexpected_token(tokens, pos))
 }
 
 /// Parse a group of atoms that start with an opening bracket and end with either a closing bracket
 /// or with an index after the closing bracket
 ///
-/// ```
+/// ```text
 /// Example K4[ON(SO3)2]2
 ///              ^^^^^^ This is a group of atoms
 ///           ^^^^^^^^^^^ This is also a group that contains another group inside of it
 /// ```
-fn parse_group(tokens: &[Token], pos: usize) -> Result<(ParseNode, usize), String> {
-    parse_open_bracket(tokens, pos).and_then(|(c, next_pos)| {
-        parse_atoms(tokens, next_pos).and_then(|(node, next_pos)| {
-            parse_close_bracket(tokens, next_pos, c).and_then(|next_pos| {
-                parse_index(tokens, next_pos).and_then(|(index, next_pos)| {
-                    let parse_node = ParseNode::new(vec![node], Grammar::Index(index));
-                    Ok((parse_node, next_pos))
-                })
-            })
-        })
-    })
+fn parse_group(tokens: &[Token], pos: usize, validate: bool) -> Result<(ParseNode, usize), SyntaxError> {
+    let (c, next_pos) = parse_open_bracket(tokens, pos)?;
+    let (node, next_pos) = parse_atoms(tokens, next_pos, validate)?;
+    let next_pos = parse_close_bracket(tokens, next_pos, c)?;
+    let (index, next_pos) = parse_index(tokens, next_pos)?;
+    let parse_node = ParseNode::new(vec![node], Grammar::Index(index));
+    Ok((parse_node, next_pos))
 }
 
 /// Parse an opening bracket for a group of atoms or returns an error.
 ///
-/// ```
+/// ```text
 /// Example K4[ON(SO3)2]2
 ///           ^  ^ These two are opening brackets
 /// ```
-fn parse_open_bracket(tokens: &[Token], pos: usize) -> Result<(char, usize), String> {
-    if let Some(&Token::Bracket(c)) = tokens.get(pos) {
-        match c {
-            '(' | '[' | '{' => Ok((c, pos + 1)),
-            _ => Err(format!("Expected opening bracket at {} but found {:?}", pos, c))
-        }
-    } else {
-        Err(format!("Unexpected token {:?}", tokens.get(pos)))
+fn parse_open_bracket(tokens: &[Token], pos: usize) -> Result<(char, usize), SyntaxError> {
+    match tokens.get(pos) {
+        Some(token) => match token.kind {
+            TokenKind::Bracket(c @ '(') | TokenKind::Bracket(c @ '[') | TokenKind::Bracket(c @ '{') => Ok((c, pos + 1)),
+            TokenKind::Bracket(c) => Err(SyntaxError::new(token.span, format!("expected an opening bracket but found '{}'", c))),
+            _ => Err(unexpected_token(tokens, pos)),
+        },
+        None => Err(unexpected_token(tokens, pos)),
     }
 }
 
 /// Parse a closing bracket for a group of atoms or returns an error.
 ///
-/// ```
+/// ```text
 /// Example K4[ON(SO3)2]2
 ///                  ^ ^ These two are closing brackets
 /// ```
-fn parse_close_bracket(tokens: &[Token], pos: usize, c: char) -> Result<usize, String> {
-    if let Some(&Token::Bracket(c2)) = tokens.get(pos) {
-        if c2 == matching(c) {
-            Ok(pos + 1)
-        } else {
-            Err(format!("Expected {} but found {} at {}", matching(c), c2, pos))
+fn parse_close_bracket(tokens: &[Token], pos: usize, c: char) -> Result<usize, SyntaxError> {
+    match tokens.get(pos) {
+        Some(token) => match token.kind {
+            TokenKind::Bracket(c2) if c2 == matching(c) => Ok(pos + 1),
+            TokenKind::Bracket(c2) => {
+                let message = format!("mismatched bracket: expected '{}' but found '{}'", matching(c), c2);
+                Err(SyntaxError::new(token.span, message))
+            },
+            _ => Err(unexpected_token(tokens, pos)),
+        },
+        None => {
+            let message = format!("mismatched bracket: expected '{}'", matching(c));
+            Err(SyntaxError::new(end_of_input(tokens), message))
         }
-    } else {
-        Err(format!("Expected closing bracket at {} but found {:?}", pos, tokens.get(pos)))
     }
 }
 
@@ -188,27 +306,193 @@ fn matching(c: char) -> char {
 
 /// Parse the index of an atom or a group of atoms.
 ///
-/// ```
+/// ```text
 /// Example K4[ON(SO3)2]2
 ///                   ^ ^ These are group indices
 ///          ^      ^ These are atom indices
 /// ```
 /// If there is one we parse it. Otherwise assume it is 1.
-fn parse_index(tokens: &[Token], pos: usize) -> Result<(usize, usize), String> {
-    if let Some(&Token::Index(n)) = tokens.get(pos) {
-        Ok((n, pos + 1))
-    } else {
-        Ok((1, pos))
+fn parse_index(tokens: &[Token], pos: usize) -> Result<(usize, usize), SyntaxError> {
+    match tokens.get(pos) {
+        Some(token) => match token.kind {
+            TokenKind::Index(n) => Ok((n, pos + 1)),
+            _ => Ok((1, pos)),
+        },
+        None => Ok((1, pos)),
+    }
+}
+
+/// Parse a slice of tokens that represents a single molecule (no
+/// reaction syntax) into a merged `Molecule`.
+///
+/// This is the shared core behind `parse_molecule` and is reused by
+/// `equation::parse_equation` to parse each side of a reaction once the
+/// input has already been split on `+` and the reaction arrow. `input`
+/// is the original source string (not just this token slice) so that
+/// errors can be rendered with the whole equation for context.
+pub(crate) fn parse_tokens(input: &str, tokens: &[Token]) -> Result<Molecule, String> {
+    parse_formula_tokens(tokens)
+        .map(|formula| formula.atoms)
+        .map_err(|e| render_error(input, e.span, &e.message))
+}
+
+/// The result of parsing a full formula: its merged atom counts plus any
+/// net ion charge, e.g. `SO4^2-` parses to atoms `[("S",1),("O",4)]`
+/// and charge `-2`.
+#[derive(Debug, Clone)]
+pub struct ParsedFormula {
+    pub atoms: Molecule,
+    pub charge: i32,
+}
+
+/// Parse a formula that may additionally contain hydrate dot-separators
+/// (`CuSO4·5H2O` or `CuSO4.5H2O`) and a trailing ion charge (`SO4^2-`,
+/// `NH4+`), on top of everything `parse_molecule` already understands.
+/// Isotope mass-number notation is not handled yet.
+pub fn parse_formula(s: &str) -> Result<ParsedFormula, String> {
+    let tokens = ::lexer::lex(s).map_err(|e| e.render(s))?;
+    parse_formula_tokens(&tokens).map_err(|e| render_error(s, e.span, &e.message))
+}
+
+/// Parse each hydrate segment (the molecule itself, then any further
+/// `.`/`·`-separated molecules) and an optional trailing charge.
+///
+/// ```text
+/// Example CuSO4.5H2O
+///         ^^^^^ first segment, multiplier 1 (implicit)
+///              ^ dot separator
+///               ^5H2O second segment, multiplier 5
+/// ```
+/// The leading multiplier of each segment after the first behaves like a
+/// group index: `5H2O` scales the whole `H2O` the same way `(H2O)5`
+/// would.
+fn parse_formula_tokens(tokens: &[Token]) -> Result<ParsedFormula, SyntaxError> {
+    let (first, mut pos) = parse_atoms(tokens, 0, true)?;
+    let mut combined = first.flatten();
+
+    while let Some(&TokenKind::DotSeparator) = tokens.get(pos).map(|t| &t.kind) {
+        pos += 1;
+        let (multiplier, next_pos) = parse_index(tokens, pos)?;
+        let (segment, next_pos) = parse_atoms(tokens, next_pos, true)?;
+        combined = [&combined[..], &mul_molecule(&segment.flatten(), multiplier)[..]].concat();
+        pos = next_pos;
     }
+
+    let atoms = merge_atoms(&combined);
+
+    let (charge, pos) = match tokens.get(pos).map(|t| &t.kind) {
+        Some(&TokenKind::Charge(c)) => (c, pos + 1),
+        _ => (0, pos),
+    };
+
+    if pos != tokens.len() {
+        let span = tokens.get(pos).map_or_else(|| end_of_input(tokens), |t| t.span);
+        return Err(SyntaxError::new(span, "not all tokens were parsed".to_string()));
+    }
+
+    Ok(ParsedFormula { atoms, charge })
 }
 
 pub fn parse_molecule(s: &str) -> Result<Molecule, String> {
+    let tokens = ::lexer::lex(s).map_err(|e| e.render(s))?;
+    parse_tokens(s, &tokens)
+}
+
+/// Parse a molecule and fold it straight into a molar mass via
+/// `ParseNode::mass`, without ever materializing the flattened
+/// `Molecule` vector.
+pub fn parse_molecule_mass(s: &str) -> Result<f64, String> {
+    let tokens = ::lexer::lex(s).map_err(|e| e.render(s))?;
+    parse_molecule_mass_inner(&tokens).map_err(|e| render_error(s, e.span, &e.message))
+}
+
+fn parse_molecule_mass_inner(tokens: &[Token]) -> Result<f64, SyntaxError> {
+    let (atoms, pos) = parse_atoms(tokens, 0, true)?;
+    if pos != tokens.len() {
+        let span = tokens.get(pos).map_or_else(|| end_of_input(tokens), |t| t.span);
+        return Err(SyntaxError::new(span, "not all tokens were parsed".to_string()));
+    }
+    Ok(atoms.mass())
+}
+
+/// What `parse_molecule_with` hands back, depending on `ParserConfig::flatten`.
+#[derive(Debug, Clone)]
+pub enum ParseOutput {
+    /// The nested syntax tree, unflattened and unmerged.
+    Tree(ParseNode),
+    /// The flattened, merged atom counts (what `parse_molecule` returns).
+    Molecule(Molecule),
+}
+
+/// Options controlling how `parse_molecule_with` interprets and returns
+/// a formula. Build one with `ParserConfig::new()` and the builder
+/// methods below; every option defaults to `parse_molecule`'s behavior.
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    flatten: bool,
+    sort: bool,
+    validate_elements: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> ParserConfig {
+        ParserConfig {
+            flatten: true,
+            sort: false,
+            validate_elements: true,
+        }
+    }
+}
+
+impl ParserConfig {
+    pub fn new() -> ParserConfig {
+        ParserConfig::default()
+    }
+
+    /// Return the flattened, merged `Molecule` (`true`, the default) or
+    /// the nested `ParseNode` tree (`false`).
+    pub fn flatten(mut self, flatten: bool) -> ParserConfig {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Sort elements with the Hill system (carbon first, hydrogen
+    /// second, then alphabetically) instead of preserving source order.
+    /// Only affects the flattened `Molecule` output. Defaults to `false`.
+    pub fn sort(mut self, sort: bool) -> ParserConfig {
+        self.sort = sort;
+        self
+    }
+
+    /// Whether every atom symbol must exist in the periodic table.
+    /// Defaults to `true`.
+    pub fn validate_elements(mut self, validate: bool) -> ParserConfig {
+        self.validate_elements = validate;
+        self
+    }
+}
+
+/// Parse a single molecule (no reaction, hydrate, or charge syntax)
+/// according to `config`, returning either the nested tree or the
+/// flattened `Molecule`, and a real `ParseError` enum instead of a
+/// caret-rendered `String`.
+pub fn parse_molecule_with(s: &str, config: &ParserConfig) -> Result<ParseOutput, ParseError> {
     let tokens = ::lexer::lex(s)?;
-    let (atoms, pos) = parse_atoms(&tokens, 0)?;
+    let (node, pos) = parse_atoms(&tokens, 0, config.validate_elements)?;
     if pos != tokens.len() {
-        return Err("Not all tokens were parsed".to_string());
+        let span = tokens.get(pos).map_or_else(|| end_of_input(&tokens), |t| t.span);
+        return Err(ParseError::Parse { span, message: "not all tokens were parsed".to_string() });
+    }
+
+    if config.flatten {
+        let mut molecule = merge_atoms(&node.flatten());
+        if config.sort {
+            hill_sort(&mut molecule);
+        }
+        Ok(ParseOutput::Molecule(molecule))
+    } else {
+        Ok(ParseOutput::Tree(node))
     }
-    Ok(merge_atoms(&atoms.flatten()))
 }
 
 
@@ -236,6 +520,81 @@ mod test {
         assert_fail("pie");
         assert_fail("Mg(OH");
         assert_fail("Mg(OH}2");
+        assert_fail("Xy");
+    }
+
+    #[test]
+    fn mismatched_bracket_error_points_at_the_bad_character() {
+        let err = parse_molecule("Mg(OH}2").unwrap_err();
+        assert!(err.contains("^"));
+        assert!(err.starts_with("Mg(OH}2\n     ^\n"));
+        assert!(err.contains("mismatched bracket"));
+    }
+
+    #[test]
+    fn water_mass() {
+        let mass = parse_molecule_mass("H2O").unwrap();
+        assert!((mass - 18.015).abs() < 0.001);
+    }
+
+    #[test]
+    fn parses_hydrate_with_dot_separator() {
+        let formula = parse_formula("CuSO4.5H2O").unwrap();
+        assert_eq!(formula.charge, 0);
+        assert!(molecules_compare(&formula.atoms, &[("Cu",1),("S",1),("O",9),("H",10)].to_vec()));
+    }
+
+    #[test]
+    fn parses_hydrate_with_middle_dot_separator() {
+        let formula = parse_formula("CuSO4\u{b7}5H2O").unwrap();
+        assert!(molecules_compare(&formula.atoms, &[("Cu",1),("S",1),("O",9),("H",10)].to_vec()));
+    }
+
+    #[test]
+    fn parses_caret_ion_charge() {
+        let formula = parse_formula("SO4^2-").unwrap();
+        assert_eq!(formula.charge, -2);
+        assert!(molecules_compare(&formula.atoms, &[("S",1),("O",4)].to_vec()));
+    }
+
+    #[test]
+    fn parses_bare_ion_charge() {
+        let formula = parse_formula("NH4+").unwrap();
+        assert_eq!(formula.charge, 1);
+        assert!(molecules_compare(&formula.atoms, &[("N",1),("H",4)].to_vec()));
+    }
+
+    #[test]
+    fn parse_molecule_with_returns_nested_tree_when_not_flattened() {
+        let config = ParserConfig::new().flatten(false);
+        match parse_molecule_with("H2O", &config).unwrap() {
+            ParseOutput::Tree(_) => {},
+            ParseOutput::Molecule(_) => panic!("expected a Tree"),
+        }
+    }
+
+    #[test]
+    fn parse_molecule_with_sorts_using_the_hill_system() {
+        let config = ParserConfig::new().sort(true);
+        match parse_molecule_with("O2H2C", &config).unwrap() {
+            ParseOutput::Molecule(molecule) => {
+                let symbols: Vec<&str> = molecule.iter().map(|a| a.0.as_str()).collect();
+                assert_eq!(symbols, vec!["C", "H", "O"]);
+            },
+            ParseOutput::Tree(_) => panic!("expected a Molecule"),
+        }
+    }
+
+    #[test]
+    fn parse_molecule_with_can_skip_element_validation() {
+        let config = ParserConfig::new().validate_elements(false);
+        assert!(parse_molecule_with("Xy", &config).is_ok());
+
+        let strict = ParserConfig::new();
+        match parse_molecule_with("Xy", &strict) {
+            Err(ParseError::UnknownElement { ref symbol, .. }) => assert_eq!(symbol, "Xy"),
+            other => panic!("expected UnknownElement, got {:?}", other),
+        }
     }
 
     fn assert_parse(molecule: &str, expected: Vec<(&str, usize)>) {